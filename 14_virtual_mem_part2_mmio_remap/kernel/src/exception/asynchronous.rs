@@ -10,7 +10,11 @@ mod arch_asynchronous;
 mod null_irq_manager;
 
 use crate::{bsp, synchronization};
-use core::{fmt, marker::PhantomData};
+use core::{
+    fmt,
+    marker::PhantomData,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 //--------------------------------------------------------------------------------------------------
 // Architectural Public Reexports
@@ -32,6 +36,78 @@ pub struct IRQDescriptor {
 
     /// Reference to handler trait object.
     pub handler: &'static (dyn interface::IRQHandler + Sync),
+
+    /// Optional deferred handler.
+    ///
+    /// Queued to run later, outside IRQ context, when `handler` returns `IrqReturn::WakeThread`.
+    pub thread_fn: Option<&'static (dyn interface::ThreadedIRQHandler + Sync)>,
+}
+
+impl IRQDescriptor {
+    /// Creates a new instance without a deferred handler.
+    ///
+    /// Existing callers built with `name`/`handler` only (from before `thread_fn` was added) can
+    /// use this instead of a struct literal.
+    pub const fn new(
+        name: &'static str,
+        handler: &'static (dyn interface::IRQHandler + Sync),
+    ) -> Self {
+        Self {
+            name,
+            handler,
+            thread_fn: None,
+        }
+    }
+}
+
+/// Maximum number of handlers a single shared IRQ line can hold in a `HandlerChain`.
+const IRQ_MAX_HANDLERS_PER_LINE: usize = 4;
+
+/// A fixed-capacity chain of handlers sharing one IRQ line.
+///
+/// This is the reference storage `IRQManager::register_handler` is documented to provide: an
+/// implementation keeps one `HandlerChain` per line (e.g. as a
+/// `[HandlerChain; IRQNumber::NUM_TOTAL]` array indexed by `IRQNumberType`), appends to it from
+/// `register_handler`, and hands the line's chain to `handle_level_irq`/`handle_edge_irq` from
+/// `handle_pending_irqs`. No heap
+/// allocator is available, so capacity is fixed rather than a linked list of heap nodes.
+#[derive(Copy, Clone)]
+pub struct HandlerChain {
+    descriptors: [Option<IRQDescriptor>; IRQ_MAX_HANDLERS_PER_LINE],
+    len: usize,
+}
+
+impl HandlerChain {
+    /// An empty chain.
+    pub const fn new() -> Self {
+        Self {
+            descriptors: [None; IRQ_MAX_HANDLERS_PER_LINE],
+            len: 0,
+        }
+    }
+
+    /// Appends `descriptor` to the chain.
+    pub fn register(&mut self, descriptor: IRQDescriptor) -> Result<(), &'static str> {
+        if self.len == IRQ_MAX_HANDLERS_PER_LINE {
+            return Err("IRQ handler chain for this line is full");
+        }
+
+        self.descriptors[self.len] = Some(descriptor);
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// The registered descriptors, in registration order.
+    pub fn iter(&self) -> impl Iterator<Item = &IRQDescriptor> {
+        self.descriptors[..self.len].iter().filter_map(Option::as_ref)
+    }
+}
+
+impl Default for HandlerChain {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// IRQContext token.
@@ -46,13 +122,86 @@ pub struct IRQContext<'irq_context> {
     _0: PhantomData<&'irq_context ()>,
 }
 
+/// IrqDisabled token.
+///
+/// An instance of this type is proof that local IRQs are currently masked on this core, as
+/// opposed to `IRQContext`, which only proves that we are executing inside a vector. Helper
+/// functions that require the invariant "IRQs are masked right now" can take `IrqDisabled<'_>` by
+/// value instead of re-asserting it themselves.
+///
+/// `!Send` and `!Sync` because the masked state belongs to the executing core; the token must not
+/// outlive it or be observed from another core.
+///
+/// Concept and implementation derived from the `IrqDisabled` guard in Rust-for-Linux.
+#[derive(Clone, Copy)]
+pub struct IrqDisabled<'a> {
+    _not_send_sync: PhantomData<(&'a (), *mut ())>,
+}
+
+impl<'a> IrqDisabled<'a> {
+    /// Creates an `IrqDisabled` token.
+    ///
+    /// # Safety
+    ///
+    /// - The caller must guarantee that local IRQs are already masked on the executing core and
+    ///   will remain so for the lifetime `'a` of the returned token.
+    #[inline(always)]
+    unsafe fn new() -> Self {
+        IrqDisabled {
+            _not_send_sync: PhantomData,
+        }
+    }
+}
+
+/// The result of running a single IRQ handler.
+///
+/// Mirrors the Linux `irqreturn_t` convention: a handler reports whether it actually serviced the
+/// interrupt it was asked about, so that a chain of handlers sharing one line can be walked until
+/// one of them claims it.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum IrqReturn {
+    /// The handler recognized and serviced the interrupt.
+    Handled,
+
+    /// The interrupt was not for this handler; try the next one in the chain.
+    None,
+
+    /// The handler recognized the interrupt but only did the minimal ack/mask work; its
+    /// `thread_fn` should be queued to run the heavy processing later, outside IRQ context.
+    WakeThread,
+}
+
 /// Asynchronous exception handling interfaces.
 pub mod interface {
+    use super::IrqReturn;
 
     /// Implemented by types that handle IRQs.
     pub trait IRQHandler {
         /// Called when the corresponding interrupt is asserted.
-        fn handle(&self) -> Result<(), &'static str>;
+        ///
+        /// Returns whether this handler actually serviced the interrupt, so that
+        /// `handle_pending_irqs` can keep walking a chain of handlers sharing the same line.
+        /// Should do the minimal ack/mask work and return `IrqReturn::WakeThread` if the bulk of
+        /// the processing can be deferred to the descriptor's `thread_fn`.
+        fn handle(&self) -> IrqReturn;
+    }
+
+    /// Implemented by the deferred bottom half of a threaded IRQ handler.
+    ///
+    /// Mirrors the split Linux's `request_threaded_irq` offers between the hard-IRQ handler and
+    /// its threaded counterpart: this runs later, outside IRQ context, with interrupts enabled, so
+    /// it may take locks and do work that would otherwise keep IRQs disabled for too long.
+    pub trait ThreadedIRQHandler {
+        /// Performs the deferred processing.
+        fn handle_threaded(&self);
+
+        /// Called once `handle_threaded` has returned.
+        ///
+        /// Implementations are expected to unmask the line here (typically by forwarding to
+        /// `IRQChip::irq_unmask` for the chip and IRQ number they were registered against), since
+        /// the line is kept masked by the flow handler for as long as the threaded handler is
+        /// pending.
+        fn complete(&self);
     }
 
     /// IRQ management functions.
@@ -64,6 +213,12 @@ pub mod interface {
         type IRQNumberType;
 
         /// Register a handler.
+        ///
+        /// Lines can be shared: calling this more than once for the same `irq_number` appends the
+        /// new descriptor to that line's handler chain instead of being rejected. Implementations
+        /// are expected to keep one [`super::HandlerChain`] per line (e.g. in an array indexed by
+        /// `IRQNumberType`) and forward to [`super::HandlerChain::register`], rather than
+        /// re-deriving fixed-capacity chain storage themselves.
         fn register_handler(
             &self,
             irq_number: Self::IRQNumberType,
@@ -80,6 +235,12 @@ pub mod interface {
         /// This function can therefore not be preempted and runs start to finish.
         ///
         /// Takes an IRQContext token to ensure it can only be called from IRQ context.
+        ///
+        /// For a shared line, implementations are expected to call each registered handler in
+        /// registration order and stop at the first one that returns `IrqReturn::Handled` or
+        /// `IrqReturn::WakeThread`. If
+        /// every handler on the line returns `IrqReturn::None`, the implementation should log a
+        /// spurious-interrupt warning and bump that line's spurious counter.
         #[allow(clippy::trivially_copy_pass_by_ref)]
         fn handle_pending_irqs<'irq_context>(
             &'irq_context self,
@@ -87,7 +248,76 @@ pub mod interface {
         );
 
         /// Print list of registered handlers.
+        ///
+        /// For a shared line, all handlers registered on it are printed alongside one another,
+        /// together with each line's current affinity.
         fn print_handler(&self) {}
+
+        /// Route `irq_number` to the cores in `mask`.
+        ///
+        /// Intended for steering SPIs away from the boot core once secondary cores are up. The
+        /// default implementation rejects the request; controllers that support per-core routing
+        /// (e.g. the GIC distributor's `ITARGETSR`/`IROUTER`) override it.
+        fn set_affinity(
+            &self,
+            irq_number: Self::IRQNumberType,
+            mask: super::CpuMask<{ super::IRQ_MAX_CORES }>,
+        ) -> Result<(), &'static str> {
+            let _ = (irq_number, mask);
+
+            Err("affinity not supported by this controller")
+        }
+
+        /// Return the mask the controller is actually honoring for `irq_number`.
+        ///
+        /// A controller may clamp an unsupported mask passed to `set_affinity` (for example, a
+        /// GIC distributor only routing SPIs to a single core at a time); callers can use this to
+        /// observe where the line really landed instead of assuming the request was applied
+        /// verbatim.
+        fn get_effective_affinity(
+            &self,
+            irq_number: Self::IRQNumberType,
+        ) -> super::CpuMask<{ super::IRQ_MAX_CORES }> {
+            let _ = irq_number;
+
+            super::CpuMask::new()
+        }
+    }
+
+    /// The per-line lifecycle operations a hardware interrupt controller provides.
+    ///
+    /// This is deliberately narrower than `IRQManager`: it only wraps the controller-specific
+    /// register pokes, while the generic level/edge sequencing lives in the free `handle_*_irq`
+    /// flow handlers in the parent module. BSPs implement this trait once per controller (e.g.
+    /// the GICv2 distributor, or the Pi's legacy controller) instead of hand-rolling the
+    /// mask/ack/eoi ordering in every `IRQManager::handle_pending_irqs`.
+    pub trait IRQChip {
+        /// The IRQ number type depends on the implementation.
+        type IRQNumberType;
+
+        /// Perform first-time setup of a line (e.g. clear any stale pending state) and unmask it.
+        fn irq_startup(&self, irq_number: Self::IRQNumberType);
+
+        /// Tear a line back down; the inverse of `irq_startup`.
+        fn irq_shutdown(&self, irq_number: Self::IRQNumberType);
+
+        /// Enable a line at the controller without touching its mask bit.
+        fn irq_enable(&self, irq_number: Self::IRQNumberType);
+
+        /// Disable a line at the controller.
+        fn irq_disable(&self, irq_number: Self::IRQNumberType);
+
+        /// Mask a line so the controller stops asserting it to the core.
+        fn irq_mask(&self, irq_number: Self::IRQNumberType);
+
+        /// Unmask a previously masked line.
+        fn irq_unmask(&self, irq_number: Self::IRQNumberType);
+
+        /// Acknowledge the asserted line, as required before it can be serviced.
+        fn irq_ack(&self, irq_number: Self::IRQNumberType);
+
+        /// Signal end-of-interrupt to the controller once the line has been serviced.
+        fn irq_eoi(&self, irq_number: Self::IRQNumberType);
     }
 }
 
@@ -95,6 +325,21 @@ pub mod interface {
 #[derive(Copy, Clone)]
 pub struct IRQNumber<const MAX_INCLUSIVE: usize>(usize);
 
+/// A bitset of CPU core indices, bounded by a const generic maximum core count.
+///
+/// Used to express IRQ affinity: which cores a shared interrupt controller (e.g. the GIC
+/// distributor) is allowed to route a line to.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct CpuMask<const MAX_CORES: usize>(u64);
+
+/// The `CpuMask` size used throughout the `IRQManager` affinity API.
+///
+/// `IRQManager::set_affinity`/`get_effective_affinity` need a fixed `MAX_CORES` that every
+/// implementor shares, since an associated const of `Self` cannot be used in const-generic
+/// position inside the trait's own default method signatures. This comfortably covers the Pi
+/// 3/4's four cores; bump it if a target with more cores is ever added.
+pub const IRQ_MAX_CORES: usize = 4;
+
 //--------------------------------------------------------------------------------------------------
 // Global instances
 //--------------------------------------------------------------------------------------------------
@@ -106,7 +351,10 @@ static CUR_IRQ_MANAGER: InitStateLock<
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
-use synchronization::{interface::ReadWriteEx, InitStateLock};
+use synchronization::{
+    interface::{Mutex, ReadWriteEx},
+    IRQSafeNullLock, InitStateLock,
+};
 
 impl<'irq_context> IRQContext<'irq_context> {
     /// Creates an IRQContext token.
@@ -148,19 +396,279 @@ impl<const MAX_INCLUSIVE: usize> fmt::Display for IRQNumber<{ MAX_INCLUSIVE }> {
     }
 }
 
+impl<const MAX_INCLUSIVE: usize> From<IRQNumber<{ MAX_INCLUSIVE }>> for usize {
+    fn from(number: IRQNumber<{ MAX_INCLUSIVE }>) -> Self {
+        number.0
+    }
+}
+
+impl<const MAX_CORES: usize> CpuMask<{ MAX_CORES }> {
+    /// An empty mask that targets no core.
+    pub const fn new() -> Self {
+        assert!(MAX_CORES <= 64);
+
+        Self(0)
+    }
+
+    /// A mask that targets exactly `core`.
+    pub const fn core(core: usize) -> Self {
+        assert!(core < MAX_CORES);
+
+        Self(1 << core)
+    }
+
+    /// Returns a copy of `self` with `core` added to the set.
+    pub const fn with_core(self, core: usize) -> Self {
+        assert!(core < MAX_CORES);
+
+        Self(self.0 | (1 << core))
+    }
+
+    /// Whether `core` is a member of this mask.
+    pub const fn contains(self, core: usize) -> bool {
+        (self.0 & (1 << core)) != 0
+    }
+
+    /// Whether this mask targets no core at all.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl<const MAX_CORES: usize> Default for CpuMask<{ MAX_CORES }> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const MAX_CORES: usize> fmt::Display for CpuMask<{ MAX_CORES }> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:0width$b}", self.0, width = MAX_CORES)
+    }
+}
+
 /// Executes the provided closure while IRQs are masked on the executing core.
 ///
 /// While the function temporarily changes the HW state of the executing core, it restores it to the
 /// previous state before returning, so this is deemed safe.
+///
+/// Hands the closure an `IrqDisabled` token, so that functions requiring a masked context can take
+/// it by value instead of re-asserting the invariant themselves.
 #[inline(always)]
-pub fn exec_with_irq_masked<T>(f: impl FnOnce() -> T) -> T {
+pub fn exec_with_irq_masked<T>(f: impl FnOnce(IrqDisabled<'_>) -> T) -> T {
     let saved = local_irq_mask_save();
-    let ret = f();
+    debug_assert!(is_local_irq_masked());
+
+    // Safety: IRQs were just masked above, and remain masked until `local_irq_restore` below.
+    let token = unsafe { IrqDisabled::new() };
+    let ret = f(token);
+
+    debug_assert!(is_local_irq_masked());
     local_irq_restore(saved);
 
     ret
 }
 
+/// Upper bound (exclusive) on the IRQ numbers spurious-interrupt counts are tracked for.
+///
+/// Covers the GIC's full interrupt ID range of 0 to 1019 (SGIs, PPIs and SPIs together); lines at
+/// or beyond this are still handled correctly, just without a counter backing them.
+const IRQ_MAX_LINES: usize = 1024;
+
+/// Per-line spurious-interrupt counters, bumped by `run_handler_chain` whenever every handler on
+/// a line returns `IrqReturn::None`.
+static SPURIOUS_IRQ_COUNTS: [AtomicUsize; IRQ_MAX_LINES] =
+    [const { AtomicUsize::new(0) }; IRQ_MAX_LINES];
+
+/// Increments the spurious-interrupt counter for `irq_number`, if it is within the tracked range.
+fn bump_spurious_count(irq_number: usize) {
+    if let Some(count) = SPURIOUS_IRQ_COUNTS.get(irq_number) {
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Returns how many times `irq_number` has gone unclaimed by every handler on its line.
+pub fn spurious_irq_count(irq_number: usize) -> usize {
+    SPURIOUS_IRQ_COUNTS
+        .get(irq_number)
+        .map_or(0, |count| count.load(Ordering::Relaxed))
+}
+
+/// Run a line's handler chain in registration order, stopping at the first handler that claims
+/// the interrupt.
+///
+/// Shared by both flow handlers below, so the chain-walking, spurious-interrupt logging and
+/// counting is defined exactly once.
+fn run_handler_chain(irq_number: usize, chain: &HandlerChain) -> IrqReturn {
+    for descriptor in chain.iter() {
+        match descriptor.handler.handle() {
+            IrqReturn::Handled => return IrqReturn::Handled,
+            IrqReturn::WakeThread => {
+                // A line left masked with no thread ever queued to unmask it again would go
+                // silently dead, so only report `WakeThread` once a thread is actually pending.
+                let queued = descriptor.thread_fn.is_some_and(queue_threaded_irq);
+
+                if queued {
+                    return IrqReturn::WakeThread;
+                }
+
+                crate::info!(
+                    "'{}' returned WakeThread without a deliverable thread_fn, treating as handled",
+                    descriptor.name
+                );
+                return IrqReturn::Handled;
+            }
+            IrqReturn::None => (),
+        }
+    }
+
+    if let Some(first) = chain.iter().next() {
+        crate::info!("Spurious interrupt on shared line '{}'", first.name);
+        bump_spurious_count(irq_number);
+    }
+
+    IrqReturn::None
+}
+
+/// Flow handler for level-triggered lines.
+///
+/// Masks and acknowledges the line before running its handler chain, then unmasks it again unless
+/// a threaded handler is now pending for it, so the controller does not re-assert the
+/// still-active level while it is being serviced. This is
+/// the sequence a level-triggered GIC SPI or the Pi's legacy controller needs; BSPs call this
+/// from their `IRQManager::handle_pending_irqs` instead of re-deriving it.
+pub fn handle_level_irq<Chip>(
+    chip: &Chip,
+    irq_number: Chip::IRQNumberType,
+    chain: &HandlerChain,
+) -> IrqReturn
+where
+    Chip: interface::IRQChip + ?Sized,
+    Chip::IRQNumberType: Copy + Into<usize>,
+{
+    chip.irq_mask(irq_number);
+    chip.irq_ack(irq_number);
+
+    let result = run_handler_chain(irq_number.into(), chain);
+
+    // Keep the line masked while a threaded handler is pending for it; it is unmasked from
+    // `ThreadedIRQHandler::complete` once the deferred work has run.
+    if result != IrqReturn::WakeThread {
+        chip.irq_unmask(irq_number);
+    }
+    chip.irq_eoi(irq_number);
+
+    result
+}
+
+/// Flow handler for edge-triggered lines.
+///
+/// Acknowledges the edge immediately, before running the handler chain, since the signal has
+/// already been latched by the controller and a further edge could otherwise arrive and be lost
+/// while the chain is still running. The caller is expected to re-check the controller's pending
+/// state after this returns and call back in if another edge arrived in the meantime.
+pub fn handle_edge_irq<Chip>(
+    chip: &Chip,
+    irq_number: Chip::IRQNumberType,
+    chain: &HandlerChain,
+) -> IrqReturn
+where
+    Chip: interface::IRQChip + ?Sized,
+    Chip::IRQNumberType: Copy + Into<usize>,
+{
+    chip.irq_ack(irq_number);
+
+    let result = run_handler_chain(irq_number.into(), chain);
+
+    // Unlike the level flow, the line isn't masked on entry, so a pending threaded handler needs
+    // it masked explicitly here; `ThreadedIRQHandler::complete` unmasks it again later.
+    if result == IrqReturn::WakeThread {
+        chip.irq_mask(irq_number);
+    }
+    chip.irq_eoi(irq_number);
+
+    result
+}
+
+//--------------------------------------------------------------------------------------------------
+// Threaded IRQ work queue
+//--------------------------------------------------------------------------------------------------
+
+/// Maximum number of threaded handlers that can be queued awaiting deferred processing.
+const MAX_PENDING_THREADED_IRQS: usize = 8;
+
+/// A bounded FIFO of threaded handlers awaiting deferred processing.
+///
+/// No heap allocator is available, so this is a fixed-capacity array instead of something like a
+/// `VecDeque`; `pop` takes the front entry and shifts the remaining ones down by one.
+struct ThreadedIrqWorkQueue {
+    pending: [Option<&'static (dyn interface::ThreadedIRQHandler + Sync)>;
+        MAX_PENDING_THREADED_IRQS],
+    len: usize,
+}
+
+impl ThreadedIrqWorkQueue {
+    const fn new() -> Self {
+        Self {
+            pending: [None; MAX_PENDING_THREADED_IRQS],
+            len: 0,
+        }
+    }
+
+    /// Returns whether `thread_fn` was actually queued.
+    fn push(&mut self, thread_fn: &'static (dyn interface::ThreadedIRQHandler + Sync)) -> bool {
+        if self.len == MAX_PENDING_THREADED_IRQS {
+            return false;
+        }
+
+        self.pending[self.len] = Some(thread_fn);
+        self.len += 1;
+
+        true
+    }
+
+    fn pop(&mut self) -> Option<&'static (dyn interface::ThreadedIRQHandler + Sync)> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let item = self.pending[0].take();
+        self.pending.copy_within(1..self.len, 0);
+        self.len -= 1;
+
+        item
+    }
+}
+
+static THREADED_IRQ_WORK_QUEUE: IRQSafeNullLock<ThreadedIrqWorkQueue> =
+    IRQSafeNullLock::new(ThreadedIrqWorkQueue::new());
+
+/// Queues a threaded handler for deferred processing.
+///
+/// Called from IRQ context by `run_handler_chain` when a hard-IRQ handler returns
+/// `IrqReturn::WakeThread`. Returns whether the handler was actually queued; the caller must not
+/// leave the originating line masked if this returns `false`, since nothing would ever unmask it.
+fn queue_threaded_irq(thread_fn: &'static (dyn interface::ThreadedIRQHandler + Sync)) -> bool {
+    let queued = THREADED_IRQ_WORK_QUEUE.lock(|q| q.push(thread_fn));
+
+    if !queued {
+        crate::info!("Threaded IRQ work queue full, dropping deferred work");
+    }
+
+    queued
+}
+
+/// Drains and runs all currently queued threaded handlers.
+///
+/// Must be called with IRQs enabled and outside IRQ context — typically from the kernel's
+/// idle/scheduler loop — since threaded handlers are expected to take their time.
+pub fn drain_threaded_irqs() {
+    while let Some(thread_fn) = THREADED_IRQ_WORK_QUEUE.lock(|q| q.pop()) {
+        thread_fn.handle_threaded();
+        thread_fn.complete();
+    }
+}
+
 /// Register a new IRQ manager.
 pub fn register_irq_manager(
     new_manager: &'static (dyn interface::IRQManager<IRQNumberType = bsp::driver::IRQNumber>